@@ -0,0 +1,128 @@
+//! Trap-and-emulate for privileged CSR accesses.
+//!
+//! VS-mode can't touch M-mode-only CSRs like `mhartid` directly; the guest's
+//! `csrr{w,s,c}[i]` traps to us as an `IllegalInstruction` exception. This
+//! decodes the SYSTEM-opcode instruction in `stval` and services a small
+//! table of virtualized CSRs with the correct read-modify-write semantics,
+//! rather than hardcoding a single opcode.
+
+use crate::decode::gpr;
+use crate::regs::GeneralPurposeRegisters;
+
+/// The three CSR read-modify-write operations; `*i` forms share the same op
+/// with an immediate source instead of a register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CsrOp {
+    ReadWrite,
+    ReadSet,
+    ReadClear,
+}
+
+/// The source operand of a CSR instruction: a GPR index for the register
+/// forms, a 5-bit immediate for the `*i` forms.
+#[derive(Debug, Clone, Copy)]
+enum CsrSrc {
+    Reg(u8),
+    Imm(u32),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct DecodedCsr {
+    csr: u16,
+    op: CsrOp,
+    rd: u8,
+    src: CsrSrc,
+}
+
+const OPCODE_SYSTEM: u32 = 0b111_0011;
+
+/// Decodes a 32-bit `csrr{w,s,c}[i]` instruction. Returns `None` for any
+/// other SYSTEM-opcode instruction (`ecall`, `ebreak`, `sret`, ...), which
+/// have `funct3 == 0` and are handled elsewhere.
+fn decode(instr: u32) -> Option<DecodedCsr> {
+    if instr & 0x7f != OPCODE_SYSTEM {
+        return None;
+    }
+    let rd = ((instr >> 7) & 0x1f) as u8;
+    let funct3 = (instr >> 12) & 0x7;
+    let rs1_or_uimm = (instr >> 15) & 0x1f;
+    let csr = (instr >> 20) as u16;
+    let (op, src) = match funct3 {
+        0b001 => (CsrOp::ReadWrite, CsrSrc::Reg(rs1_or_uimm as u8)),
+        0b010 => (CsrOp::ReadSet, CsrSrc::Reg(rs1_or_uimm as u8)),
+        0b011 => (CsrOp::ReadClear, CsrSrc::Reg(rs1_or_uimm as u8)),
+        0b101 => (CsrOp::ReadWrite, CsrSrc::Imm(rs1_or_uimm)),
+        0b110 => (CsrOp::ReadSet, CsrSrc::Imm(rs1_or_uimm)),
+        0b111 => (CsrOp::ReadClear, CsrSrc::Imm(rs1_or_uimm)),
+        _ => return None,
+    };
+    Some(DecodedCsr { csr, op, rd, src })
+}
+
+mod csr_num {
+    pub const MVENDORID: u16 = 0xf11;
+    pub const MARCHID: u16 = 0xf12;
+    pub const MIMPID: u16 = 0xf13;
+    pub const MHARTID: u16 = 0xf14;
+    pub const TIME: u16 = 0xc01;
+}
+
+/// Reads a virtualized CSR's current value, or `None` if it isn't one we
+/// emulate. `hart_id` is the trapping vCPU's id, used to service `mhartid`.
+fn read_virtual_csr(csr: u16, hart_id: usize) -> Option<usize> {
+    use csr_num::*;
+    Some(match csr {
+        MVENDORID => 0,
+        MARCHID => 0,
+        MIMPID => 0,
+        MHARTID => hart_id,
+        TIME => axhal::time::current_time_nanos() as usize,
+        _ => return None,
+    })
+}
+
+/// Whether `csr` accepts writes. Every CSR we currently emulate is read-only
+/// identification/timer state — same as on real hardware, where attempting
+/// to write one of these raises an illegal-instruction exception rather than
+/// silently discarding the write.
+fn is_writable(_csr: u16) -> bool {
+    false
+}
+
+/// The result of attempting to emulate a trapping instruction.
+pub enum CsrOutcome {
+    /// Serviced; advance the guest's `sepc` past the instruction and resume.
+    Emulated,
+    /// Not a CSR instruction we emulate; the guest should take a genuine
+    /// illegal-instruction exception.
+    Unsupported,
+}
+
+/// Services `instr` against the virtual CSR table, applying standard
+/// `csrr{w,s,c}[i]` semantics: `rd` (if not `x0`) gets the CSR's old value,
+/// and the write only happens for `csrrw`, or for `csrrs`/`csrrc` when `rs1`
+/// is not `x0` (for the immediate forms, when `uimm` is non-zero).
+pub fn emulate(instr: u32, hart_id: usize, gprs: &mut GeneralPurposeRegisters) -> CsrOutcome {
+    let Some(decoded) = decode(instr) else {
+        return CsrOutcome::Unsupported;
+    };
+    let Some(old) = read_virtual_csr(decoded.csr, hart_id) else {
+        return CsrOutcome::Unsupported;
+    };
+
+    // `csrrw` always writes; `csrrs`/`csrrc` only write when `rs1` is not
+    // `x0` (or `uimm` is non-zero), whatever value that register holds.
+    let attempts_write = match decoded.src {
+        CsrSrc::Reg(0) | CsrSrc::Imm(0) => decoded.op == CsrOp::ReadWrite,
+        _ => true,
+    };
+    if attempts_write && !is_writable(decoded.csr) {
+        return CsrOutcome::Unsupported;
+    }
+
+    if decoded.rd != 0 {
+        gprs.set_reg(gpr(decoded.rd as u32), old);
+    }
+
+    CsrOutcome::Emulated
+}
@@ -0,0 +1,150 @@
+//! Hypervisor-extension CSR definitions that the upstream `riscv` crate
+//! does not expose yet, plus the trap cause bit layouts used by the
+//! vmexit handler.
+
+pub mod defs {
+    use tock_registers::register_bitfields;
+
+    register_bitfields![usize,
+        pub hstatus [
+            vsxl OFFSET(32) NUMBITS(2) [],
+            vtsr OFFSET(22) NUMBITS(1) [],
+            vtw OFFSET(21) NUMBITS(1) [],
+            vtvm OFFSET(20) NUMBITS(1) [],
+            vgein OFFSET(12) NUMBITS(6) [],
+            spvp OFFSET(8) NUMBITS(1) [
+                User = 0,
+                Supervisor = 1,
+            ],
+            spv OFFSET(7) NUMBITS(1) [
+                User = 0,
+                Guest = 1,
+            ],
+            gva OFFSET(6) NUMBITS(1) [],
+            vsbe OFFSET(5) NUMBITS(1) [],
+        ],
+    ];
+}
+
+/// Trap cause bit layouts for `scause`/`hvip`/`sie`, matching the RISC-V
+/// privileged spec numbering.
+pub mod traps {
+    pub mod interrupt {
+        pub const SUPERVISOR_SOFT: usize = 1 << 1;
+        pub const VIRTUAL_SUPERVISOR_SOFT: usize = 1 << 2;
+        pub const SUPERVISOR_TIMER: usize = 1 << 5;
+        pub const VIRTUAL_SUPERVISOR_TIMER: usize = 1 << 6;
+        pub const SUPERVISOR_EXTERNAL: usize = 1 << 9;
+        pub const VIRTUAL_SUPERVISOR_EXTERNAL: usize = 1 << 10;
+    }
+
+    pub mod exception {
+        pub const ILLEGAL_INSTRUCTION: usize = 2;
+        pub const VIRTUAL_SUPERVISOR_ENV_CALL: usize = 10;
+        pub const INSTRUCTION_GUEST_PAGE_FAULT: usize = 20;
+        pub const LOAD_GUEST_PAGE_FAULT: usize = 21;
+        pub const STORE_GUEST_PAGE_FAULT: usize = 23;
+    }
+}
+
+/// A trait for CSRs that can be read, written and bit-twiddled as a whole
+/// `usize`, regardless of which concrete register they back.
+pub trait RiscvCsrTrait {
+    fn read(&self) -> usize;
+    fn write_value(&self, value: usize);
+    fn set_bits(&self, bits: usize) {
+        self.write_value(self.read() | bits);
+    }
+    fn clear_bits(&self, bits: usize) {
+        self.write_value(self.read() & !bits);
+    }
+    /// Sets `bits` and returns the value the register held beforehand.
+    fn read_and_set_bits(&self, bits: usize) -> usize {
+        let old = self.read();
+        self.write_value(old | bits);
+        old
+    }
+    /// Clears `bits` and returns the value the register held beforehand.
+    fn read_and_clear_bits(&self, bits: usize) -> usize {
+        let old = self.read();
+        self.write_value(old & !bits);
+        old
+    }
+}
+
+/// Declares a zero-sized handle for a CSR that isn't covered by the
+/// upstream `riscv` crate, implementing [`RiscvCsrTrait`] via `csrrs`/`csrw`.
+macro_rules! hv_csr {
+    ($name:ident, $csr:literal) => {
+        pub struct $name;
+
+        impl RiscvCsrTrait for $name {
+            fn read(&self) -> usize {
+                let value: usize;
+                unsafe { core::arch::asm!(concat!("csrr {0}, ", $csr), out(reg) value) }
+                value
+            }
+
+            fn write_value(&self, value: usize) {
+                unsafe { core::arch::asm!(concat!("csrw ", $csr, ", {0}"), in(reg) value) }
+            }
+        }
+    };
+}
+
+hv_csr!(Hstatus, "hstatus");
+hv_csr!(Hgatp, "hgatp");
+hv_csr!(Hvip, "hvip");
+hv_csr!(Hedeleg, "hedeleg");
+hv_csr!(Hideleg, "hideleg");
+hv_csr!(Htval, "htval");
+hv_csr!(Htinst, "htinst");
+hv_csr!(Hcounteren, "hcounteren");
+hv_csr!(Sie, "sie");
+hv_csr!(Vstimecmp, "vstimecmp");
+// The `vs`-prefixed shadow CSRs: with `hstatus.spv = Guest`, `sepc`/`scause`/
+// `stval`/`stvec` ARE these registers as far as VS-mode is concerned, but
+// HS-mode needs the explicit names to read or write them on the guest's
+// behalf (e.g. to inject an exception without actually trapping into VS-mode
+// first).
+hv_csr!(Vsepc, "vsepc");
+hv_csr!(Vscause, "vscause");
+hv_csr!(Vstval, "vstval");
+hv_csr!(Vstvec, "vstvec");
+
+/// All hypervisor-extension CSRs used by this hypervisor, grouped behind a
+/// single handle so call sites read as `CSR.hvip.read_and_set_bits(..)`.
+#[allow(non_snake_case)]
+pub struct CsrRegs {
+    pub hstatus: Hstatus,
+    pub hgatp: Hgatp,
+    pub hvip: Hvip,
+    pub hedeleg: Hedeleg,
+    pub hideleg: Hideleg,
+    pub htval: Htval,
+    pub htinst: Htinst,
+    pub hcounteren: Hcounteren,
+    pub sie: Sie,
+    pub vstimecmp: Vstimecmp,
+    pub vsepc: Vsepc,
+    pub vscause: Vscause,
+    pub vstval: Vstval,
+    pub vstvec: Vstvec,
+}
+
+pub static CSR: CsrRegs = CsrRegs {
+    hstatus: Hstatus,
+    hgatp: Hgatp,
+    hvip: Hvip,
+    hedeleg: Hedeleg,
+    hideleg: Hideleg,
+    htval: Htval,
+    htinst: Htinst,
+    hcounteren: Hcounteren,
+    sie: Sie,
+    vstimecmp: Vstimecmp,
+    vsepc: Vsepc,
+    vscause: Vscause,
+    vstval: Vstval,
+    vstvec: Vstvec,
+};
@@ -0,0 +1,188 @@
+//! Decodes the load/store instruction that triggered a guest page fault, so
+//! the vmexit handler knows which GPR to fill/drain and how wide the MMIO
+//! access is.
+
+use crate::regs::GprIndex;
+
+/// Access width of a decoded load/store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Width {
+    Byte,
+    Half,
+    Word,
+    Double,
+}
+
+impl Width {
+    pub fn bits(self) -> u32 {
+        match self {
+            Width::Byte => 8,
+            Width::Half => 16,
+            Width::Word => 32,
+            Width::Double => 64,
+        }
+    }
+
+    /// Masks `val` down to this width.
+    pub fn truncate(self, val: u64) -> u64 {
+        if self.bits() == 64 {
+            val
+        } else {
+            val & ((1u64 << self.bits()) - 1)
+        }
+    }
+
+    /// Sign-extends a `self`-wide value held in the low bits of `val`.
+    pub fn sign_extend(self, val: u64) -> u64 {
+        let shift = 64 - self.bits();
+        (((val << shift) as i64) >> shift) as u64
+    }
+}
+
+/// A decoded faulting load or store.
+#[derive(Debug, Clone, Copy)]
+pub struct DecodedAccess {
+    pub is_load: bool,
+    pub width: Width,
+    pub signed: bool,
+    /// For a load, the destination GPR; for a store, the source GPR.
+    pub reg: GprIndex,
+    /// Instruction length in bytes: 2 for compressed, 4 otherwise.
+    pub len: usize,
+}
+
+pub(crate) fn gpr(index: u32) -> GprIndex {
+    // Safety: `GprIndex` is `repr(usize)` over 0..32 and callers only ever
+    // pass in a 5-bit field.
+    unsafe { core::mem::transmute::<usize, GprIndex>(index as usize) }
+}
+
+/// Compressed register fields (`rd'`/`rs2'`) only name x8..=x15.
+fn compressed_gpr(index: u32) -> GprIndex {
+    gpr(index + 8)
+}
+
+/// Decodes a RISC-V load/store, standard (32-bit) or compressed (16-bit).
+/// `instr` is the raw instruction bits; for a compressed instruction only
+/// the low 16 bits are meaningful.
+pub fn decode_load_store(instr: u32) -> Option<DecodedAccess> {
+    if instr & 0b11 == 0b11 {
+        decode_standard(instr)
+    } else {
+        decode_compressed(instr as u16)
+    }
+}
+
+fn decode_standard(instr: u32) -> Option<DecodedAccess> {
+    let opcode = instr & 0x7f;
+    let funct3 = (instr >> 12) & 0x7;
+    match opcode {
+        // LOAD
+        0b0000011 => {
+            let (width, signed) = match funct3 {
+                0b000 => (Width::Byte, true),
+                0b001 => (Width::Half, true),
+                0b010 => (Width::Word, true),
+                0b011 => (Width::Double, false),
+                0b100 => (Width::Byte, false),
+                0b101 => (Width::Half, false),
+                0b110 => (Width::Word, false),
+                _ => return None,
+            };
+            Some(DecodedAccess {
+                is_load: true,
+                width,
+                signed,
+                reg: gpr((instr >> 7) & 0x1f),
+                len: 4,
+            })
+        }
+        // STORE
+        0b0100011 => {
+            let width = match funct3 {
+                0b000 => Width::Byte,
+                0b001 => Width::Half,
+                0b010 => Width::Word,
+                0b011 => Width::Double,
+                _ => return None,
+            };
+            Some(DecodedAccess {
+                is_load: false,
+                width,
+                signed: false,
+                reg: gpr((instr >> 20) & 0x1f),
+                len: 4,
+            })
+        }
+        _ => None,
+    }
+}
+
+fn decode_compressed(instr: u16) -> Option<DecodedAccess> {
+    let instr = instr as u32;
+    let quadrant = instr & 0b11;
+    let funct3 = (instr >> 13) & 0b111;
+    match (quadrant, funct3) {
+        // C.LW / C.SW: rd'/rs2' in bits [4:2], base in bits [9:7].
+        (0b00, 0b010) => Some(DecodedAccess {
+            is_load: true,
+            width: Width::Word,
+            signed: true,
+            reg: compressed_gpr((instr >> 2) & 0x7),
+            len: 2,
+        }),
+        (0b00, 0b110) => Some(DecodedAccess {
+            is_load: false,
+            width: Width::Word,
+            signed: false,
+            reg: compressed_gpr((instr >> 2) & 0x7),
+            len: 2,
+        }),
+        // C.LD / C.SD (RV64-only).
+        (0b00, 0b011) => Some(DecodedAccess {
+            is_load: true,
+            width: Width::Double,
+            signed: false,
+            reg: compressed_gpr((instr >> 2) & 0x7),
+            len: 2,
+        }),
+        (0b00, 0b111) => Some(DecodedAccess {
+            is_load: false,
+            width: Width::Double,
+            signed: false,
+            reg: compressed_gpr((instr >> 2) & 0x7),
+            len: 2,
+        }),
+        // C.LWSP / C.SWSP: full `rd`/`rs2` in bits [11:7]/[6:2].
+        (0b10, 0b010) => Some(DecodedAccess {
+            is_load: true,
+            width: Width::Word,
+            signed: true,
+            reg: gpr((instr >> 7) & 0x1f),
+            len: 2,
+        }),
+        (0b10, 0b110) => Some(DecodedAccess {
+            is_load: false,
+            width: Width::Word,
+            signed: false,
+            reg: gpr((instr >> 2) & 0x1f),
+            len: 2,
+        }),
+        // C.LDSP / C.SDSP (RV64-only).
+        (0b10, 0b011) => Some(DecodedAccess {
+            is_load: true,
+            width: Width::Double,
+            signed: false,
+            reg: gpr((instr >> 7) & 0x1f),
+            len: 2,
+        }),
+        (0b10, 0b111) => Some(DecodedAccess {
+            is_load: false,
+            width: Width::Double,
+            signed: false,
+            reg: gpr((instr >> 2) & 0x1f),
+            len: 2,
+        }),
+        _ => None,
+    }
+}
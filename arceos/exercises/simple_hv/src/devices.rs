@@ -0,0 +1,104 @@
+//! Trap-and-emulate MMIO: devices register for a guest-physical address
+//! range and are dispatched to from the vmexit handler on guest page
+//! faults that land inside that range.
+
+use alloc::boxed::Box;
+use core::ops::Range;
+
+use crate::decode::Width;
+
+/// A device that can be mapped into the guest's physical address space
+/// without a real backing page, trapping every access instead.
+pub trait MmioDevice {
+    /// Services a guest load. The result is truncated/zero-filled by the
+    /// caller to `width` before being sign/zero-extended into the GPR.
+    fn mmio_read(&mut self, addr: usize, width: Width) -> u64;
+
+    /// Services a guest store of `val`, already masked to `width`.
+    fn mmio_write(&mut self, addr: usize, width: Width, val: u64);
+}
+
+/// The set of MMIO devices visible to a guest, keyed by guest-physical
+/// address range.
+#[derive(Default)]
+pub struct MmioBus {
+    devices: alloc::vec::Vec<(Range<usize>, Box<dyn MmioDevice + Send>)>,
+}
+
+impl MmioBus {
+    pub const fn new() -> Self {
+        Self {
+            devices: alloc::vec::Vec::new(),
+        }
+    }
+
+    /// Registers `device` to handle any access whose guest-physical address
+    /// falls in `range`.
+    pub fn register(&mut self, range: Range<usize>, device: Box<dyn MmioDevice + Send>) {
+        self.devices.push((range, device));
+    }
+
+    /// Finds the device (if any) covering `gpa`.
+    pub fn find(&mut self, gpa: usize) -> Option<&mut (dyn MmioDevice + Send)> {
+        self.devices
+            .iter_mut()
+            .find(|(range, _)| range.contains(&gpa))
+            .map(|(_, dev)| dev.as_mut())
+    }
+}
+
+/// A minimal 16550-compatible UART that forwards the guest's console I/O to
+/// the host's own UART.
+pub struct Uart16550 {
+    /// Guest-physical base address this device is mapped at; register
+    /// offsets are computed relative to it.
+    pub base: usize,
+    /// A byte read from the host console while servicing an LSR poll, held
+    /// until the guest actually reads RBR so it isn't dropped.
+    pending: Option<u8>,
+}
+
+impl Uart16550 {
+    pub const fn new(base: usize) -> Self {
+        Self { base, pending: None }
+    }
+
+    fn poll_host(&mut self) -> Option<u8> {
+        if self.pending.is_none() {
+            self.pending = axhal::console::getchar();
+        }
+        self.pending
+    }
+}
+
+// 16550 register offsets (DLAB=0).
+const REG_RBR_THR: usize = 0; // receiver buffer / transmit holding
+const REG_LSR: usize = 5; // line status
+
+// LSR bits the guest polls before reading/writing.
+const LSR_DATA_READY: u64 = 1 << 0;
+const LSR_THR_EMPTY: u64 = 1 << 5;
+
+impl MmioDevice for Uart16550 {
+    fn mmio_read(&mut self, addr: usize, _width: Width) -> u64 {
+        match addr - self.base {
+            REG_RBR_THR => self.pending.take().or_else(|| axhal::console::getchar())
+                .map(u64::from)
+                .unwrap_or(0),
+            REG_LSR => {
+                let mut lsr = LSR_THR_EMPTY;
+                if self.poll_host().is_some() {
+                    lsr |= LSR_DATA_READY;
+                }
+                lsr
+            }
+            _ => 0,
+        }
+    }
+
+    fn mmio_write(&mut self, addr: usize, _width: Width, val: u64) {
+        if addr - self.base == REG_RBR_THR {
+            axhal::console::putchar(val as u8);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! Lazily-populated guest-physical memory.
+//!
+//! Instead of mapping the whole guest image into the stage-2 page table up
+//! front, `main` only records which GPA ranges exist and what backs them.
+//! Pages are faulted in one at a time from `vmexit_handler`, mirroring the
+//! frame-allocator + memory-set + page-table split of an rCore-style
+//! kernel, just one translation stage up.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use axhal::mem::{virt_to_phys, PhysAddr};
+use axmm::backend::MappingFlags;
+use axmm::AddrSpace;
+use memory_addr::{MemoryAddr, VirtAddr, PAGE_SIZE_4K as PAGE_SIZE};
+
+/// A guest-physical region, backed either by a loaded image or by fresh
+/// anonymous memory.
+struct Region {
+    range: Range<usize>,
+    /// File-backed data, indexed from `range.start`. Any part of `range`
+    /// past the end of `image` is zero-filled, same as a BSS tail.
+    image: Option<Arc<[u8]>>,
+}
+
+/// Why a guest-physical access couldn't be serviced.
+#[derive(Debug)]
+pub enum FaultOutcome {
+    /// `gpa` isn't covered by any registered region at all.
+    OutOfRange,
+}
+
+/// The guest's physical memory map, as seen from the stage-2 page table.
+pub struct GuestPhysMemory {
+    regions: Vec<Region>,
+}
+
+impl Default for GuestPhysMemory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GuestPhysMemory {
+    pub const fn new() -> Self {
+        Self {
+            regions: Vec::new(),
+        }
+    }
+
+    pub fn add_image_backed(&mut self, range: Range<usize>, image: Arc<[u8]>) {
+        self.regions.push(Region {
+            range,
+            image: Some(image),
+        });
+    }
+
+    pub fn add_anonymous(&mut self, range: Range<usize>) {
+        self.regions.push(Region { range, image: None });
+    }
+
+    /// `true` if `gpa` falls in a registered region (whether or not the
+    /// backing page has been faulted in yet).
+    pub fn covers(&self, gpa: usize) -> bool {
+        self.regions.iter().any(|r| r.range.contains(&gpa))
+    }
+
+    /// Allocates a host frame for the guest page containing `gpa`, fills it
+    /// from the backing image (if any), and installs it into `aspace`'s
+    /// stage-2 page table with `flags`.
+    pub fn populate(
+        &self,
+        aspace: &mut AddrSpace,
+        gpa: usize,
+        flags: MappingFlags,
+    ) -> Result<(), FaultOutcome> {
+        let region = self
+            .regions
+            .iter()
+            .find(|r| r.range.contains(&gpa))
+            .ok_or(FaultOutcome::OutOfRange)?;
+
+        let page_gpa = gpa & !(PAGE_SIZE - 1);
+        let frame_vaddr = axalloc::global_allocator()
+            .alloc_pages(1, PAGE_SIZE)
+            .expect("out of memory populating guest page");
+        let frame_ptr = frame_vaddr as *mut u8;
+
+        unsafe { core::ptr::write_bytes(frame_ptr, 0, PAGE_SIZE) };
+        if let Some(image) = &region.image {
+            let page_off = page_gpa - region.range.start;
+            if page_off < image.len() {
+                let len = core::cmp::min(PAGE_SIZE, image.len() - page_off);
+                unsafe {
+                    core::ptr::copy_nonoverlapping(image[page_off..].as_ptr(), frame_ptr, len);
+                }
+            }
+        }
+
+        let paddr: PhysAddr = virt_to_phys(frame_vaddr.into());
+        aspace
+            .page_table()
+            .map(VirtAddr::from(page_gpa), paddr, PAGE_SIZE, flags)
+            .expect("failed to install stage-2 mapping");
+
+        unsafe {
+            core::arch::riscv64::hfence_gvma(page_gpa, 0);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,39 @@
+//! Reads a guest kernel image and reserves its guest-physical range, without
+//! eagerly populating it — pages are faulted in on demand, see [`crate::gpm`].
+
+use alloc::sync::Arc;
+use axerrno::AxResult;
+use axmm::AddrSpace;
+use memory_addr::{MemoryAddr, VirtAddr, PAGE_SIZE_4K as PAGE_SIZE};
+
+use crate::VM_ENTRY;
+
+/// Guest physical memory is mapped 1:1 with the guest virtual addresses the
+/// image is linked at, starting at [`VM_ENTRY`].
+const VM_ASPACE_SIZE: usize = 0x80_0000;
+
+/// Reads `path` from the host filesystem and reserves its range in `uspace`
+/// (without populating it), returning the image bytes so the caller can
+/// register them as the backing store for demand paging.
+pub fn load_vm_image(path: &str, uspace: &mut AddrSpace) -> AxResult<Arc<[u8]>> {
+    let image: Arc<[u8]> = axstd::fs::read(path)?.into();
+
+    uspace.map_alloc(
+        VirtAddr::from(VM_ENTRY),
+        VM_ASPACE_SIZE,
+        axmm::backend::MappingFlags::READ
+            | axmm::backend::MappingFlags::WRITE
+            | axmm::backend::MappingFlags::EXECUTE
+            | axmm::backend::MappingFlags::USER,
+        /* populate = */ false,
+    )?;
+
+    Ok(image)
+}
+
+/// Guest-physical range the image occupies, rounded up to a whole number of
+/// pages.
+pub fn image_gpa_range(image_len: usize) -> core::ops::Range<usize> {
+    let end = (VM_ENTRY + image_len + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+    VM_ENTRY..end
+}
@@ -9,27 +9,43 @@ extern crate axstd as std;
 #[macro_use]
 extern crate axlog;
 
+mod csr_emu;
 mod csrs;
+mod decode;
+mod devices;
+mod gpm;
 mod loader;
 mod regs;
 mod sbi;
 mod task;
 mod vcpu;
 
+use alloc::boxed::Box;
 use crate::csrs::traps;
-use crate::regs::GprIndex::{A0, A1};
+use crate::devices::{MmioBus, Uart16550};
+use crate::gpm::{FaultOutcome, GuestPhysMemory};
+use axmm::backend::MappingFlags;
+use axmm::AddrSpace;
 use axhal::mem::PhysAddr;
 use csrs::defs::hstatus;
 use csrs::{RiscvCsrTrait, CSR};
 use loader::load_vm_image;
 use riscv::register::scause::Interrupt;
 use riscv::register::{scause, sstatus, stval};
-use sbi::SbiMessage;
+use sbi::{SbiMessage, SbiOutcome, SbiReturn};
+use task::{HartState, VmCpus, VmTask};
 use tock_registers::LocalRegisterCopy;
 use vcpu::VmCpuRegisters;
 use vcpu::_run_guest;
 
 const VM_ENTRY: usize = 0x8020_0000;
+/// Guest-physical base of the emulated 16550 UART (matches the QEMU `virt`
+/// machine's `UART0`).
+const UART_BASE: usize = 0x1000_0000;
+const UART_SIZE: usize = 0x100;
+/// Number of vCPUs made available to the guest; secondary harts boot
+/// stopped and wait for the primary to `hart_start` them via SBI HSM.
+const MAX_HARTS: usize = 4;
 
 #[cfg_attr(feature = "axstd", no_mangle)]
 fn main() {
@@ -38,21 +54,51 @@ fn main() {
     // A new address space for vm.
     let mut uspace = axmm::new_user_aspace().unwrap();
 
-    // Load vm binary file into address space.
-    if let Err(e) = load_vm_image("/sbin/skernel2", &mut uspace) {
-        panic!("Cannot load app! {:?}", e);
-    }
+    // Reserve (but don't yet populate) the guest image's GPA range; pages
+    // are faulted in lazily as the guest touches them.
+    let image = load_vm_image("/sbin/skernel2", &mut uspace)
+        .unwrap_or_else(|e| panic!("Cannot load app! {:?}", e));
+    let mut gpm = GuestPhysMemory::new();
+    gpm.add_image_backed(loader::image_gpa_range(image.len()), image);
 
     // Setup context to prepare to enter guest mode.
     let mut ctx = VmCpuRegisters::default();
     prepare_guest_context(&mut ctx);
 
+    // Hart 0 boots straight into the guest; secondary harts start parked
+    // and wait for the guest to SBI `hart_start` them. Every vCPU needs the
+    // same VS-mode privilege setup (`hstatus.spv`/`spvp`, `sstatus.spp`) so
+    // that whatever entry point `hart_start` points it at is reached in
+    // VS-S rather than VS-U; only the entry `sepc` differs, and `VmTask::start`
+    // fills that in once the guest actually starts the hart.
+    let mut cpus = VmCpus::new();
+    cpus.add_hart(VmTask::new(0, ctx, HartState::Started));
+    for hart_id in 1..MAX_HARTS {
+        let mut ctx = VmCpuRegisters::default();
+        init_guest_mode(&mut ctx);
+        cpus.add_hart(VmTask::new(hart_id, ctx, HartState::Stopped));
+    }
+
     // Setup pagetable for 2nd address mapping.
     let ept_root = uspace.page_table_root();
     prepare_vm_pgtable(ept_root);
 
-    // Kick off vm and wait for it to exit.
-    while !run_guest(&mut ctx) {}
+    // Register the devices the guest is allowed to trap-and-emulate against.
+    let mut mmio = MmioBus::new();
+    mmio.register(
+        UART_BASE..UART_BASE + UART_SIZE,
+        Box::new(Uart16550::new(UART_BASE)),
+    );
+
+    // Round-robin across whichever harts are runnable until none are left,
+    // i.e. every vCPU has SBI `hart_stop`ped or the guest shut down.
+    let mut current = 0;
+    while let Some(hart_id) = cpus.next_runnable(current) {
+        current = hart_id;
+        if run_guest(&mut cpus, current, &mut mmio, &mut uspace, &gpm) {
+            break;
+        }
+    }
 
     panic!("Hypervisor ok!");
 }
@@ -68,94 +114,172 @@ fn prepare_vm_pgtable(ept_root: PhysAddr) {
     }
 }
 
-fn run_guest(ctx: &mut VmCpuRegisters) -> bool {
-    ax_println!("Entering guest...");
+fn run_guest(
+    cpus: &mut VmCpus,
+    current: usize,
+    mmio: &mut MmioBus,
+    aspace: &mut AddrSpace,
+    gpm: &GuestPhysMemory,
+) -> bool {
+    ax_println!("Entering guest (hart {})...", current);
     unsafe {
-        _run_guest(ctx);
+        _run_guest(&mut cpus.hart_mut(current).unwrap().regs);
     }
-    ax_println!("Returned from guest.");
+    ax_println!("Returned from guest (hart {}).", current);
 
-    vmexit_handler(ctx)
+    vmexit_handler(cpus, current, mmio, aspace, gpm)
 }
 
 #[allow(unreachable_code)]
-fn vmexit_handler(ctx: &mut VmCpuRegisters) -> bool {
+fn vmexit_handler(
+    cpus: &mut VmCpus,
+    current: usize,
+    mmio: &mut MmioBus,
+    aspace: &mut AddrSpace,
+    gpm: &GuestPhysMemory,
+) -> bool {
     use scause::{Exception, Trap};
 
     let scause = scause::read();
     match scause.cause() {
         Trap::Exception(Exception::VirtualSupervisorEnvCall) => {
-            let sbi_msg = SbiMessage::from_regs(ctx.guest_regs.gprs.a_regs()).ok();
+            let sbi_msg =
+                SbiMessage::from_regs(cpus.hart_mut(current).unwrap().regs.guest_regs.gprs.a_regs());
             ax_println!("VmExit Reason: VSuperEcall: {:?}", sbi_msg);
-            if let Some(msg) = sbi_msg {
-                match msg {
-                    SbiMessage::Reset(_) => {
-                        let a0 = ctx.guest_regs.gprs.reg(A0);
-                        let a1 = ctx.guest_regs.gprs.reg(A1);
-                        ax_println!("a0 = {:#x}, a1 = {:#x}", a0, a1);
-                        assert_eq!(a0, 0x6688);
-                        assert_eq!(a1, 0x1234);
-                        ax_println!("Shutdown vm normally!");
-
-                        // ctx.guest_regs.sepc += 4;
-                        return true;
-                    }
-                    _ => {
-                        // ctx.guest_regs.sepc += 4;
-                    }
+
+            let outcome = match sbi_msg {
+                Ok(msg) => sbi::dispatch(msg, cpus, current),
+                Err(_) => SbiOutcome::Handled(SbiReturn::unsupported()),
+            };
+
+            let hart = cpus.hart_mut(current).unwrap();
+            hart.regs.guest_regs.sepc += 4;
+            match outcome {
+                SbiOutcome::Handled(ret) => {
+                    sbi::write_return(&mut hart.regs.guest_regs.gprs, ret);
+                }
+                SbiOutcome::Shutdown => {
+                    ax_println!("Shutdown vm normally!");
+                    return true;
                 }
-            } else {
-                panic!("bad sbi message! ");
             }
         }
         Trap::Exception(Exception::IllegalInstruction) => {
             let instr = stval::read() as u32;
-            // 根据指令类型进行相应处理
-            if instr == 0xf14025f3 {
-                ctx.guest_regs.gprs.set_reg(A0, 0x6688);
-                ctx.guest_regs.gprs.set_reg(A1, 0x1234);
-                // 处理完成后调整 sepc
-                ctx.guest_regs.sepc += 4;
+            let hart = cpus.hart_mut(current).unwrap();
+            match csr_emu::emulate(instr, hart.hart_id, &mut hart.regs.guest_regs.gprs) {
+                csr_emu::CsrOutcome::Emulated => {
+                    hart.regs.guest_regs.sepc += 4;
+                }
+                csr_emu::CsrOutcome::Unsupported => {
+                    inject_illegal_instruction(hart, instr as usize);
+                }
+            }
+            return false;
+        }
+        Trap::Exception(Exception::InstructionGuestPageFault) => {
+            let gpa = (CSR.htval.read() << 2) | (stval::read() & 0xfff);
+            match gpm.populate(aspace, gpa, MappingFlags::READ | MappingFlags::EXECUTE) {
+                Ok(()) => return false, // retry the faulting fetch; sepc unchanged
+                Err(FaultOutcome::OutOfRange) => {
+                    ax_println!(
+                        "Guest fault: no backing for instruction fetch at gpa {:#x}, sepc {:#x}",
+                        gpa,
+                        cpus.hart_mut(current).unwrap().regs.guest_regs.sepc
+                    );
+                    return true;
+                }
+            }
+        }
+        Trap::Exception(Exception::LoadGuestPageFault)
+        | Trap::Exception(Exception::StoreGuestPageFault) => {
+            let is_store = matches!(scause.cause(), Trap::Exception(Exception::StoreGuestPageFault));
+            let gpa = (CSR.htval.read() << 2) | (stval::read() & 0xfff);
+
+            let flags = if is_store {
+                MappingFlags::READ | MappingFlags::WRITE
+            } else {
+                MappingFlags::READ
+            };
+            match gpm.populate(aspace, gpa, flags) {
+                Ok(()) => return false, // lazily-backed page faulted in; retry the access
+                Err(FaultOutcome::OutOfRange) => {}
+            }
+
+            // Not backed by guest RAM — see if it's an emulated device.
+            let sepc = cpus.hart_mut(current).unwrap().regs.guest_regs.sepc;
+            let htinst = CSR.htinst.read();
+            let raw_instr = fetch_faulting_instruction(sepc, htinst);
+            // When non-zero, `htinst` holds the *transformed* instruction in
+            // 32-bit standard form, with bit 1 telling us whether the original
+            // access was compressed (0) or not (1) — the low two bits of
+            // the transform itself don't reflect that, so OR bit 1 back in
+            // and always decode it as standard, then fix up `len` from the
+            // bit `decode_standard` can't see.
+            let decode_instr = if htinst != 0 { raw_instr | 0b10 } else { raw_instr };
+            let mut access = decode::decode_load_store(decode_instr).unwrap_or_else(|| {
+                panic!(
+                    "Cannot decode faulting {} instruction {:#x} at sepc {:#x}",
+                    if is_store { "store" } else { "load" },
+                    raw_instr,
+                    sepc
+                )
+            });
+            if htinst != 0 {
+                access.len = if (htinst >> 1) & 1 == 0 { 2 } else { 4 };
+            }
+
+            let Some(dev) = mmio.find(gpa) else {
                 ax_println!(
-                    "Bad instruction: {:#x} sepc: {:#x}",
-                    stval::read(),
-                    ctx.guest_regs.sepc
+                    "Guest fault: no handler for gpa {:#x} ({} access), sepc {:#x}",
+                    gpa,
+                    if is_store { "store" } else { "load" },
+                    sepc
                 );
+                return true;
+            };
 
-                return false;
+            let hart = cpus.hart_mut(current).unwrap();
+            if access.is_load {
+                let raw = dev.mmio_read(gpa, access.width);
+                let val = access.width.truncate(raw);
+                let val = if access.signed {
+                    access.width.sign_extend(val)
+                } else {
+                    val
+                };
+                hart.regs.guest_regs.gprs.set_reg(access.reg, val as usize);
+            } else {
+                let val = hart.regs.guest_regs.gprs.reg(access.reg) as u64;
+                dev.mmio_write(gpa, access.width, access.width.truncate(val));
             }
 
-            panic!(
-                "Bad instruction: {:#x} sepc: {:#x}",
-                stval::read(),
-                ctx.guest_regs.sepc
-            );
-        }
-        Trap::Exception(Exception::LoadGuestPageFault) => {
-            ctx.guest_regs.sepc += 4;
-            
+            hart.regs.guest_regs.sepc += access.len;
             return false;
-            panic!(
-                "LoadGuestPageFault: stval{:#x} sepc: {:#x}",
-                stval::read(),
-                ctx.guest_regs.sepc
-            );
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
-            info!("timer irq emulation");
-            // Enable guest timer interrupt
-            CSR.hvip
-                .read_and_set_bits(traps::interrupt::VIRTUAL_SUPERVISOR_TIMER);
-            // Clear host timer interrupt
-            CSR.sie
-                .read_and_clear_bits(traps::interrupt::SUPERVISOR_TIMER);
+            let hart = cpus.hart_mut(current).unwrap();
+            if hart.deadline_elapsed() {
+                info!("timer irq emulation: injecting into guest");
+                // Enable guest timer interrupt
+                CSR.hvip
+                    .read_and_set_bits(traps::interrupt::VIRTUAL_SUPERVISOR_TIMER);
+                // Clear host timer interrupt
+                CSR.sie
+                    .read_and_clear_bits(traps::interrupt::SUPERVISOR_TIMER);
+                hart.cancel_timer();
+            } else if let Some(deadline) = hart.pending_deadline() {
+                // The host timer fired early relative to what the guest
+                // asked for; just re-arm it for the real deadline.
+                axhal::time::set_oneshot_timer(deadline);
+            }
             return false;
         }
         _ => {
             panic!(
                 "Unhandled trap: {:?}, sepc: {:#x}, stval: {:#x}",
                 scause.cause(),
-                ctx.guest_regs.sepc,
+                cpus.hart_mut(current).unwrap().regs.guest_regs.sepc,
                 stval::read()
             );
         }
@@ -163,7 +287,47 @@ fn vmexit_handler(ctx: &mut VmCpuRegisters) -> bool {
     false
 }
 
+/// Delivers a virtual illegal-instruction exception to the guest for a
+/// `csrr{w,s,c}[i]` we don't emulate, instead of panicking the hypervisor.
+/// The hardware only lets us inject *interrupts* via `hvip`; an exception
+/// has to be delivered by hand through the `vs`-prefixed shadow CSRs, which
+/// VS-mode sees as its own `scause`/`stval`/`sepc` once we resume it.
+fn inject_illegal_instruction(hart: &mut VmTask, faulting_instr: usize) {
+    CSR.vscause.write_value(traps::exception::ILLEGAL_INSTRUCTION);
+    CSR.vstval.write_value(faulting_instr);
+    CSR.vsepc.write_value(hart.regs.guest_regs.sepc);
+    hart.regs.guest_regs.sepc = CSR.vstvec.read();
+}
+
+/// Recovers the instruction that trapped with a guest page fault: prefers
+/// the transformed instruction the hardware already placed in `htinst`,
+/// otherwise fetches it from guest memory at `sepc` via `hlvx`, which
+/// performs the GVA->GPA->HPA two-stage translation for us.
+fn fetch_faulting_instruction(sepc: usize, htinst: usize) -> u32 {
+    if htinst != 0 {
+        return htinst as u32;
+    }
+    unsafe {
+        let lo = core::arch::riscv64::hlvx_hu(sepc as *const u16);
+        if lo & 0b11 == 0b11 {
+            let hi = core::arch::riscv64::hlvx_hu((sepc + 2) as *const u16);
+            ((hi as u32) << 16) | lo as u32
+        } else {
+            lo as u32
+        }
+    }
+}
+
 fn prepare_guest_context(ctx: &mut VmCpuRegisters) {
+    init_guest_mode(ctx);
+    // Return to entry to start vm.
+    ctx.guest_regs.sepc = VM_ENTRY;
+}
+
+/// Sets up the VS-mode privilege state (`hstatus.spv`/`spvp`,
+/// `sstatus.spp`) every vCPU needs to enter the guest in VS-S rather than
+/// VS-U, regardless of which `sepc` it eventually starts at.
+fn init_guest_mode(ctx: &mut VmCpuRegisters) {
     // Set hstatus
     let mut hstatus =
         LocalRegisterCopy::<usize, hstatus::Register>::new(riscv::register::hstatus::read().bits());
@@ -178,6 +342,4 @@ fn prepare_guest_context(ctx: &mut VmCpuRegisters) {
     let mut sstatus = sstatus::read();
     sstatus.set_spp(sstatus::SPP::Supervisor);
     ctx.guest_regs.sstatus = sstatus.bits();
-    // Return to entry to start vm.
-    ctx.guest_regs.sepc = VM_ENTRY;
 }
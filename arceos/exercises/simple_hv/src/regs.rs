@@ -0,0 +1,74 @@
+//! RISC-V general purpose register definitions shared between the
+//! hypervisor and the trap/context-switch assembly.
+
+/// Index of a RISC-V general purpose register, named after its ABI name.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[repr(usize)]
+pub enum GprIndex {
+    Zero = 0,
+    RA,
+    SP,
+    GP,
+    TP,
+    T0,
+    T1,
+    T2,
+    S0,
+    S1,
+    A0,
+    A1,
+    A2,
+    A3,
+    A4,
+    A5,
+    A6,
+    A7,
+    S2,
+    S3,
+    S4,
+    S5,
+    S6,
+    S7,
+    S8,
+    S9,
+    S10,
+    S11,
+    T3,
+    T4,
+    T5,
+    T6,
+}
+
+impl GprIndex {
+    pub fn to_num(self) -> usize {
+        self as usize
+    }
+}
+
+/// The general purpose registers of a guest (or host) context.
+#[derive(Default, Copy, Clone, Debug)]
+#[repr(C)]
+pub struct GeneralPurposeRegisters([usize; 32]);
+
+impl GeneralPurposeRegisters {
+    /// Returns the value of the given register.
+    pub fn reg(&self, index: GprIndex) -> usize {
+        self.0[index.to_num()]
+    }
+
+    /// Sets the value of the given register.
+    pub fn set_reg(&mut self, index: GprIndex, value: usize) {
+        self.0[index.to_num()] = value;
+    }
+
+    /// Returns the `a0..=a7` argument registers, as used by the SBI calling
+    /// convention.
+    pub fn a_regs(&self) -> &[usize] {
+        &self.0[GprIndex::A0.to_num()..=GprIndex::A7.to_num()]
+    }
+
+    /// Returns a mutable view of the `a0..=a7` argument registers.
+    pub fn a_regs_mut(&mut self) -> &mut [usize] {
+        &mut self.0[GprIndex::A0.to_num()..=GprIndex::A7.to_num()]
+    }
+}
@@ -0,0 +1,311 @@
+//! SBI v1.0 call decoding and dispatch.
+//!
+//! Modeled on the `sbi` crate: the extension id (`a7`) and function id
+//! (`a6`) are decoded off the guest's argument registers into a
+//! [`SbiMessage`], which [`dispatch`] then services. Unimplemented
+//! extensions/functions return `SBI_ERR_NOT_SUPPORTED` instead of being
+//! silently ignored.
+
+use crate::regs::GeneralPurposeRegisters;
+use crate::task::{HartState, HsmError, VmCpus};
+
+pub mod extension_id {
+    pub const LEGACY_SET_TIMER: usize = 0x00;
+    pub const LEGACY_CONSOLE_PUTCHAR: usize = 0x01;
+    pub const LEGACY_CONSOLE_GETCHAR: usize = 0x02;
+    pub const BASE: usize = 0x10;
+    pub const HSM: usize = 0x48534D; // "HSM"
+    pub const TIME: usize = 0x5449_4D45; // "TIME"
+    pub const SRST: usize = 0x5352_5354; // "SRST"
+}
+
+pub mod time_function_id {
+    pub const SET_TIMER: usize = 0;
+}
+
+pub mod hsm_function_id {
+    pub const HART_START: usize = 0;
+    pub const HART_STOP: usize = 1;
+    pub const HART_GET_STATUS: usize = 2;
+}
+
+pub mod base_function_id {
+    pub const GET_SPEC_VERSION: usize = 0;
+    pub const GET_IMPL_ID: usize = 1;
+    pub const GET_IMPL_VERSION: usize = 2;
+    pub const PROBE_EXTENSION: usize = 3;
+    pub const GET_MVENDORID: usize = 4;
+    pub const GET_MARCHID: usize = 5;
+    pub const GET_MIMPID: usize = 6;
+}
+
+/// SBI error codes, per the calling-convention chapter of the spec.
+pub mod error_code {
+    pub const SBI_SUCCESS: isize = 0;
+    pub const SBI_ERR_NOT_SUPPORTED: isize = -2;
+    pub const SBI_ERR_INVALID_PARAM: isize = -3;
+    pub const SBI_ERR_ALREADY_AVAILABLE: isize = -6;
+}
+
+/// Our own id for the impl, returned from `base::get_impl_id`.
+const ARCEOS_HV_IMPL_ID: usize = 0xa5ce05;
+const ARCEOS_HV_IMPL_VERSION: usize = 1;
+const SBI_SPEC_VERSION: usize = 0x0001_0000; // v1.0
+
+#[derive(Debug, Clone, Copy)]
+pub enum BaseFunction {
+    GetSpecVersion,
+    GetImplId,
+    GetImplVersion,
+    ProbeExtension(usize),
+    GetMVendorId,
+    GetMArchId,
+    GetMImpId,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ResetType {
+    Shutdown,
+    ColdReboot,
+    WarmReboot,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ResetFunction {
+    pub reset_type: ResetType,
+    pub reset_reason: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum HsmFunction {
+    HartStart {
+        hart_id: usize,
+        start_addr: usize,
+        opaque: usize,
+    },
+    HartStop,
+    HartGetStatus {
+        hart_id: usize,
+    },
+}
+
+/// A decoded SBI call, ready to be serviced by [`dispatch`].
+#[derive(Debug, Clone, Copy)]
+pub enum SbiMessage {
+    Base(BaseFunction),
+    Hsm(HsmFunction),
+    /// `sbi_set_timer`, from either the legacy (ext 0x00) or the TIME
+    /// extension; answered with the `(error, value)` pair in both cases.
+    SetTimer(u64),
+    /// Legacy (ext 0x01) `sbi_console_putchar`.
+    PutChar(u8),
+    /// Legacy (ext 0x02) `sbi_console_getchar`.
+    GetChar,
+    Reset(ResetFunction),
+}
+
+/// An SBI call whose extension or function id this hypervisor doesn't (yet)
+/// implement.
+#[derive(Debug)]
+pub struct SbiUnsupported;
+
+impl SbiMessage {
+    /// Decodes an SBI call from the guest's `a0..=a7` argument registers.
+    pub fn from_regs(a: &[usize]) -> Result<Self, SbiUnsupported> {
+        let eid = a[7];
+        let fid = a[6];
+        use extension_id::*;
+        match eid {
+            LEGACY_SET_TIMER => Ok(SbiMessage::SetTimer(a[0] as u64)),
+            LEGACY_CONSOLE_PUTCHAR => Ok(SbiMessage::PutChar(a[0] as u8)),
+            LEGACY_CONSOLE_GETCHAR => Ok(SbiMessage::GetChar),
+            BASE => BaseFunction::from_regs(fid, a).map(SbiMessage::Base),
+            HSM => HsmFunction::from_regs(fid, a).map(SbiMessage::Hsm),
+            TIME if fid == time_function_id::SET_TIMER => {
+                Ok(SbiMessage::SetTimer(a[0] as u64))
+            }
+            SRST => {
+                let reset_type = match a[0] {
+                    0 => ResetType::Shutdown,
+                    1 => ResetType::ColdReboot,
+                    2 => ResetType::WarmReboot,
+                    _ => return Err(SbiUnsupported),
+                };
+                Ok(SbiMessage::Reset(ResetFunction {
+                    reset_type,
+                    reset_reason: a[1],
+                }))
+            }
+            _ => Err(SbiUnsupported),
+        }
+    }
+}
+
+impl HsmFunction {
+    fn from_regs(fid: usize, a: &[usize]) -> Result<Self, SbiUnsupported> {
+        use hsm_function_id::*;
+        Ok(match fid {
+            HART_START => HsmFunction::HartStart {
+                hart_id: a[0],
+                start_addr: a[1],
+                opaque: a[2],
+            },
+            HART_STOP => HsmFunction::HartStop,
+            HART_GET_STATUS => HsmFunction::HartGetStatus { hart_id: a[0] },
+            _ => return Err(SbiUnsupported),
+        })
+    }
+}
+
+impl BaseFunction {
+    fn from_regs(fid: usize, a: &[usize]) -> Result<Self, SbiUnsupported> {
+        use base_function_id::*;
+        Ok(match fid {
+            GET_SPEC_VERSION => BaseFunction::GetSpecVersion,
+            GET_IMPL_ID => BaseFunction::GetImplId,
+            GET_IMPL_VERSION => BaseFunction::GetImplVersion,
+            PROBE_EXTENSION => BaseFunction::ProbeExtension(a[0]),
+            GET_MVENDORID => BaseFunction::GetMVendorId,
+            GET_MARCHID => BaseFunction::GetMArchId,
+            GET_MIMPID => BaseFunction::GetMImpId,
+            _ => return Err(SbiUnsupported),
+        })
+    }
+}
+
+/// The `(error, value)` pair an SBI call writes back into `a0`/`a1`.
+#[derive(Debug, Clone, Copy)]
+pub struct SbiReturn {
+    pub error: isize,
+    pub value: usize,
+    /// Legacy (ext 0x00-0x0F) extensions predate the `(error, value)`
+    /// convention and return their single result directly in `a0`.
+    legacy: bool,
+}
+
+impl SbiReturn {
+    pub fn success(value: usize) -> Self {
+        Self {
+            error: error_code::SBI_SUCCESS,
+            value,
+            legacy: false,
+        }
+    }
+
+    pub fn failure(error: isize) -> Self {
+        Self {
+            error,
+            value: 0,
+            legacy: false,
+        }
+    }
+
+    pub fn unsupported() -> Self {
+        Self::failure(error_code::SBI_ERR_NOT_SUPPORTED)
+    }
+
+    /// A legacy-extension result: `value` goes straight into `a0`.
+    pub fn legacy(value: usize) -> Self {
+        Self {
+            error: error_code::SBI_SUCCESS,
+            value,
+            legacy: true,
+        }
+    }
+}
+
+/// The outcome of servicing one SBI call.
+pub enum SbiOutcome {
+    /// Write `SbiReturn` back into the guest's `a0`/`a1` and resume it.
+    Handled(SbiReturn),
+    /// The guest asked to be shut down; the vmexit loop should stop.
+    Shutdown,
+}
+
+/// Services a decoded SBI call, covering the Base, HSM, Timer, legacy
+/// console and SRST extensions. Extensions this hypervisor doesn't
+/// implement are caught by [`SbiMessage::from_regs`] before this is ever
+/// reached. `current_hart` is the hart id of the vCPU making the call.
+pub fn dispatch(msg: SbiMessage, cpus: &mut VmCpus, current_hart: usize) -> SbiOutcome {
+    let ret = match msg {
+        SbiMessage::Base(f) => SbiReturn::success(match f {
+            BaseFunction::GetSpecVersion => SBI_SPEC_VERSION,
+            BaseFunction::GetImplId => ARCEOS_HV_IMPL_ID,
+            BaseFunction::GetImplVersion => ARCEOS_HV_IMPL_VERSION,
+            BaseFunction::ProbeExtension(ext) => probe_extension(ext) as usize,
+            BaseFunction::GetMVendorId => 0,
+            BaseFunction::GetMArchId => 0,
+            BaseFunction::GetMImpId => 0,
+        }),
+        SbiMessage::Hsm(f) => match f {
+            HsmFunction::HartStart {
+                hart_id,
+                start_addr,
+                opaque,
+            } => match cpus.start_hart(hart_id, start_addr, opaque) {
+                Ok(()) => SbiReturn::success(0),
+                Err(HsmError::InvalidHartId) => SbiReturn::failure(error_code::SBI_ERR_INVALID_PARAM),
+                Err(HsmError::AlreadyStarted) => {
+                    SbiReturn::failure(error_code::SBI_ERR_ALREADY_AVAILABLE)
+                }
+            },
+            HsmFunction::HartStop => match cpus.stop_hart(current_hart) {
+                Ok(()) => SbiReturn::success(0),
+                Err(_) => SbiReturn::failure(error_code::SBI_ERR_INVALID_PARAM),
+            },
+            HsmFunction::HartGetStatus { hart_id } => match cpus.hart_status(hart_id) {
+                Ok(state) => SbiReturn::success(state as usize),
+                Err(HsmError::InvalidHartId) => SbiReturn::failure(error_code::SBI_ERR_INVALID_PARAM),
+                Err(HsmError::AlreadyStarted) => unreachable!(),
+            },
+        },
+        SbiMessage::SetTimer(deadline) => {
+            if let Some(hart) = cpus.hart_mut(current_hart) {
+                hart.set_timer(deadline);
+            }
+            SbiReturn::success(0)
+        }
+        SbiMessage::PutChar(c) => {
+            axhal::console::putchar(c);
+            SbiReturn::legacy(0)
+        }
+        SbiMessage::GetChar => {
+            // Legacy convention: no byte available is reported as -1, not
+            // as a valid (if implausible) character.
+            let value = match axhal::console::getchar() {
+                Some(c) => c as usize,
+                None => -1isize as usize,
+            };
+            SbiReturn::legacy(value)
+        }
+        SbiMessage::Reset(_) => return SbiOutcome::Shutdown,
+    };
+    SbiOutcome::Handled(ret)
+}
+
+fn probe_extension(ext: usize) -> bool {
+    use extension_id::*;
+    matches!(
+        ext,
+        LEGACY_SET_TIMER
+            | LEGACY_CONSOLE_PUTCHAR
+            | LEGACY_CONSOLE_GETCHAR
+            | BASE
+            | HSM
+            | TIME
+            | SRST
+    )
+}
+
+/// Writes `ret` back into the guest's `a0`/`a1`, per the SBI calling
+/// convention. A result built with [`SbiReturn::legacy`] is the exception:
+/// its value goes straight into `a0` instead of the `(error, value)` pair.
+pub fn write_return(gprs: &mut GeneralPurposeRegisters, ret: SbiReturn) {
+    use crate::regs::GprIndex::{A0, A1};
+    if ret.legacy {
+        gprs.set_reg(A0, ret.value);
+        return;
+    }
+    gprs.set_reg(A0, ret.error as usize);
+    gprs.set_reg(A1, ret.value);
+}
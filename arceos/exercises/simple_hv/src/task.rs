@@ -0,0 +1,173 @@
+//! The hypervisor's notion of a guest "task": one vCPU's register and
+//! virtual-timer state, plus [`VmCpus`], the collection of all of a guest's
+//! vCPUs and the HSM state machine/round-robin scheduler over them.
+
+use alloc::vec::Vec;
+
+use crate::csrs::{traps, RiscvCsrTrait, CSR};
+use crate::regs::GprIndex;
+use crate::vcpu::VmCpuRegisters;
+
+/// SBI HSM hart states (the values match the SBI spec's
+/// `sbi_hart_get_status` return codes).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HartState {
+    Started = 0,
+    Stopped = 1,
+    StartPending = 2,
+}
+
+/// A single vCPU: its architectural register state, HSM state, and
+/// outstanding virtual timer deadline.
+pub struct VmTask {
+    pub hart_id: usize,
+    pub regs: VmCpuRegisters,
+    pub state: HartState,
+    /// The deadline the guest last requested via `sbi_set_timer`/TIME, in
+    /// the same units as the `time` CSR. `None` if nothing is outstanding.
+    timer_deadline: Option<u64>,
+}
+
+impl VmTask {
+    pub fn new(hart_id: usize, regs: VmCpuRegisters, state: HartState) -> Self {
+        Self {
+            hart_id,
+            regs,
+            state,
+            timer_deadline: None,
+        }
+    }
+
+    /// Records the guest's requested deadline and arms the host timer for
+    /// it. Safe to call again to reprogram an already-pending deadline.
+    pub fn set_timer(&mut self, deadline: u64) {
+        self.timer_deadline = Some(deadline);
+        // A previous injection masked the host timer interrupt and left the
+        // virtual one pending; undo both so the new deadline takes effect.
+        CSR.hvip
+            .clear_bits(traps::interrupt::VIRTUAL_SUPERVISOR_TIMER);
+        CSR.sie.set_bits(traps::interrupt::SUPERVISOR_TIMER);
+        axhal::time::set_oneshot_timer(deadline);
+    }
+
+    /// Clears any outstanding guest deadline, e.g. once it has fired.
+    pub fn cancel_timer(&mut self) {
+        self.timer_deadline = None;
+    }
+
+    /// The guest's next requested deadline, for a scheduler to pick the
+    /// earliest across vCPUs/VMs.
+    pub fn pending_deadline(&self) -> Option<u64> {
+        self.timer_deadline
+    }
+
+    /// Whether the outstanding deadline (if any) has actually elapsed. The
+    /// host timer can fire early relative to what the guest asked for, so
+    /// this must be checked before injecting the virtual interrupt.
+    pub fn deadline_elapsed(&self) -> bool {
+        self.timer_deadline
+            .map(|deadline| axhal::time::current_time_nanos() >= deadline)
+            .unwrap_or(false)
+    }
+
+    /// Parks this vCPU; it won't be scheduled again until [`VmCpus::start_hart`].
+    fn stop(&mut self) {
+        self.state = HartState::Stopped;
+    }
+
+    /// Arranges for this (currently stopped) vCPU to start at `start_addr`
+    /// with `a0 = hart_id`, `a1 = opaque`, per the SBI HSM calling
+    /// convention for `hart_start`.
+    fn start(&mut self, start_addr: usize, opaque: usize) {
+        self.regs.guest_regs.sepc = start_addr;
+        self.regs
+            .guest_regs
+            .gprs
+            .set_reg(GprIndex::A0, self.hart_id);
+        self.regs.guest_regs.gprs.set_reg(GprIndex::A1, opaque);
+        self.state = HartState::StartPending;
+    }
+}
+
+/// Errors the SBI HSM extension reports back to the guest.
+#[derive(Debug, Clone, Copy)]
+pub enum HsmError {
+    InvalidHartId,
+    AlreadyStarted,
+}
+
+/// All of a guest's vCPUs, and the round-robin scheduler that picks which
+/// one `main`'s loop runs next.
+pub struct VmCpus {
+    harts: Vec<VmTask>,
+}
+
+impl Default for VmCpus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VmCpus {
+    pub fn new() -> Self {
+        Self { harts: Vec::new() }
+    }
+
+    pub fn add_hart(&mut self, task: VmTask) {
+        self.harts.push(task);
+    }
+
+    pub fn hart_mut(&mut self, hart_id: usize) -> Option<&mut VmTask> {
+        self.harts.iter_mut().find(|h| h.hart_id == hart_id)
+    }
+
+    pub fn hart(&self, hart_id: usize) -> Option<&VmTask> {
+        self.harts.iter().find(|h| h.hart_id == hart_id)
+    }
+
+    /// SBI `hart_start`: moves a stopped hart to [`HartState::StartPending`].
+    pub fn start_hart(
+        &mut self,
+        hart_id: usize,
+        start_addr: usize,
+        opaque: usize,
+    ) -> Result<(), HsmError> {
+        let hart = self.hart_mut(hart_id).ok_or(HsmError::InvalidHartId)?;
+        if hart.state != HartState::Stopped {
+            return Err(HsmError::AlreadyStarted);
+        }
+        hart.start(start_addr, opaque);
+        Ok(())
+    }
+
+    /// SBI `hart_stop`: parks `hart_id`.
+    pub fn stop_hart(&mut self, hart_id: usize) -> Result<(), HsmError> {
+        let hart = self.hart_mut(hart_id).ok_or(HsmError::InvalidHartId)?;
+        hart.stop();
+        Ok(())
+    }
+
+    /// SBI `hart_get_status`.
+    pub fn hart_status(&self, hart_id: usize) -> Result<HartState, HsmError> {
+        self.hart(hart_id).map(|h| h.state).ok_or(HsmError::InvalidHartId)
+    }
+
+    /// Round-robins starting just after `after`, returning the next hart id
+    /// that's runnable (`Started` or `StartPending`), promoting a
+    /// `StartPending` hart to `Started` as it's picked up. `None` if every
+    /// hart is stopped, i.e. the guest has nothing left to run.
+    pub fn next_runnable(&mut self, after: usize) -> Option<usize> {
+        let n = self.harts.len();
+        let after_pos = self.harts.iter().position(|h| h.hart_id == after)?;
+        for i in 1..=n {
+            let idx = (after_pos + i) % n;
+            if self.harts[idx].state != HartState::Stopped {
+                if self.harts[idx].state == HartState::StartPending {
+                    self.harts[idx].state = HartState::Started;
+                }
+                return Some(self.harts[idx].hart_id);
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,39 @@
+//! Per-vCPU register state and the host/guest context switch.
+
+use crate::regs::GeneralPurposeRegisters;
+
+/// Registers that are saved/restored across a guest entry/exit, mirroring
+/// the layout the external `_run_guest` routine expects.
+#[derive(Default, Copy, Clone)]
+#[repr(C)]
+pub struct GuestCpuState {
+    pub gprs: GeneralPurposeRegisters,
+    pub sstatus: usize,
+    pub hstatus: usize,
+    pub scounteren: usize,
+    pub sepc: usize,
+}
+
+/// Host state saved across the trip into guest mode, restored on vmexit.
+#[derive(Default, Copy, Clone)]
+#[repr(C)]
+pub struct HostCpuState {
+    pub gprs: GeneralPurposeRegisters,
+    pub sstatus: usize,
+    pub scounteren: usize,
+}
+
+/// All register state for a single vCPU.
+#[derive(Default, Copy, Clone)]
+#[repr(C)]
+pub struct VmCpuRegisters {
+    pub guest_regs: GuestCpuState,
+    pub host_regs: HostCpuState,
+}
+
+extern "C" {
+    /// Enters guest mode with `ctx`, returning (via a vmexit trap) once the
+    /// guest traps back into the hypervisor. Saves host state into
+    /// `ctx.host_regs` and restores it before returning.
+    pub fn _run_guest(ctx: *mut VmCpuRegisters);
+}
@@ -1,6 +1,7 @@
 #![no_std]
 extern crate alloc;
 
+use alloc::vec::Vec;
 use core::alloc::Layout;
 use core::ptr::NonNull;
 use allocator::{AllocError, AllocResult, BaseAllocator, ByteAllocator, PageAllocator};
@@ -124,3 +125,113 @@ impl<const PAGE_SIZE: usize> PageAllocator for EarlyAllocator<PAGE_SIZE> {
         self.available_bytes() / PAGE_SIZE
     }
 }
+
+/// Reclaiming page allocator, in the rCore "stack allocator" style.
+///
+/// Unlike `EarlyAllocator`, freed frames actually come back: `alloc_pages`
+/// first tries the `recycled` free-list before bumping `current` forward,
+/// and `dealloc_pages` just pushes the freed frame onto that list. Meant to
+/// take over page allocation once early boot is done — see [`Self::init_from`].
+pub struct StackPageAllocator<const PAGE_SIZE: usize> {
+    start: usize,
+    current: usize, // next bump-allocation start, grows from `start` towards `end`
+    end: usize,
+    recycled: Vec<usize>,
+}
+
+impl<const PAGE_SIZE: usize> StackPageAllocator<PAGE_SIZE> {
+    pub const fn new() -> Self {
+        Self {
+            start: 0,
+            current: 0,
+            end: 0,
+            recycled: Vec::new(),
+        }
+    }
+}
+
+impl<const PAGE_SIZE: usize> Default for StackPageAllocator<PAGE_SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const PAGE_SIZE: usize> StackPageAllocator<PAGE_SIZE> {
+    /// Hands off the unused `[b_pos, p_pos)` window left over in `early` to
+    /// this allocator, so frames freed from here on are actually returned to
+    /// the pool instead of leaking for the rest of boot.
+    pub fn init_from(&mut self, early: &EarlyAllocator<PAGE_SIZE>) {
+        self.init(early.b_pos, early.p_pos - early.b_pos);
+    }
+}
+
+impl<const PAGE_SIZE: usize> BaseAllocator for StackPageAllocator<PAGE_SIZE> {
+    fn init(&mut self, start: usize, size: usize) {
+        self.start = start;
+        self.current = start;
+        self.end = start + size;
+        self.recycled.clear();
+    }
+
+    fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        if self.start == self.end {
+            // Nothing handed to us yet; treat this as our initial region.
+            self.init(start, size);
+            Ok(())
+        } else if start == self.end {
+            // Directly adjacent to what we already have: just extend it.
+            self.end += size;
+            Ok(())
+        } else {
+            Err(AllocError::InvalidParam)
+        }
+    }
+}
+
+impl<const PAGE_SIZE: usize> PageAllocator for StackPageAllocator<PAGE_SIZE> {
+    const PAGE_SIZE: usize = PAGE_SIZE;
+
+    fn alloc_pages(&mut self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
+        let align = 1 << align_pow2;
+        if num_pages == 1 {
+            if let Some(pos) = self.recycled.pop() {
+                if pos & (align - 1) == 0 {
+                    return Ok(pos);
+                }
+                // Wrong alignment for this request; leave it recycled and
+                // fall back to bump-allocating a fresh frame instead.
+                self.recycled.push(pos);
+            }
+        }
+
+        let total_size = num_pages * PAGE_SIZE;
+        let aligned = (self.current + align - 1) & !(align - 1);
+        if aligned + total_size <= self.end {
+            self.current = aligned + total_size;
+            Ok(aligned)
+        } else {
+            Err(AllocError::NoMemory)
+        }
+    }
+
+    fn dealloc_pages(&mut self, pos: usize, num_pages: usize) {
+        debug_assert_eq!(num_pages, 1, "StackPageAllocator only recycles single frames");
+        debug_assert!(
+            pos < self.current && !self.recycled.contains(&pos),
+            "double free of frame {pos:#x}",
+        );
+        self.recycled.push(pos);
+    }
+
+    fn total_pages(&self) -> usize {
+        (self.end - self.start) / PAGE_SIZE
+    }
+
+    fn used_pages(&self) -> usize {
+        (self.current - self.start) / PAGE_SIZE - self.recycled.len()
+    }
+
+    fn available_pages(&self) -> usize {
+        self.total_pages() - self.used_pages()
+    }
+}
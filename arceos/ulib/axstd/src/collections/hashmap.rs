@@ -1,20 +1,30 @@
 use alloc::vec::Vec;
 use core::hash::{BuildHasher, BuildHasherDefault, Hash, Hasher};
 
+/// The large odd constant FxHash mixes each word by; chosen so multiplying
+/// by it scrambles bits across the whole word instead of just the low ones,
+/// unlike a multiply-by-31 byte-at-a-time rolling hash, which
+/// clusters badly (e.g. small integer keys differing in one byte land in
+/// nearby buckets).
+const FXHASH_SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+
 #[derive(Default)]
-pub struct SimpleHasher(u64);
-impl Hasher for SimpleHasher {
+pub struct FxHasher(u64);
+impl Hasher for FxHasher {
     fn finish(&self) -> u64 {
         self.0
     }
     fn write(&mut self, bytes: &[u8]) {
-        for byte in bytes {
-            self.0 = self.0.wrapping_mul(31).wrapping_add(*byte as u64);
+        for chunk in bytes.chunks(8) {
+            let mut word = [0u8; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            let word = u64::from_ne_bytes(word);
+            self.0 = (self.0.rotate_left(5) ^ word).wrapping_mul(FXHASH_SEED);
         }
     }
 }
 
-type DefaultHasher = BuildHasherDefault<SimpleHasher>;
+type DefaultHasher = BuildHasherDefault<FxHasher>;
 
 #[derive(Debug)]
 pub struct HashMap<K, V> {
@@ -93,6 +103,83 @@ impl<K: Eq + Hash, V> HashMap<K, V> {
         None
     }
 
+    /// Removes `key`, returning its value if present.
+    ///
+    /// Uses backward-shift deletion instead of leaving a tombstone: after
+    /// vacating the slot, the entries following it are walked forward up to
+    /// the next empty slot, and each one whose ideal bucket does not lie
+    /// between the gap and its own slot is shifted back into the gap, which
+    /// then moves to where that entry was. Entries that would become
+    /// unreachable from their ideal bucket stay put. This preserves the
+    /// invariant `get` relies on — a `None` always terminates the probe for
+    /// good.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let mut index = self.hash(key);
+        for _ in 0..self.buckets.len() {
+            match &self.buckets[index] {
+                Some((k, _)) if k == key => {
+                    let (_, value) = self.buckets[index].take().unwrap();
+                    self.backward_shift(index);
+                    self.size -= 1;
+                    return Some(value);
+                }
+                None => return None,
+                _ => {
+                    index = (index + 1) % self.buckets.len();
+                }
+            }
+        }
+        None
+    }
+
+    fn backward_shift(&mut self, mut hole: usize) {
+        let cap = self.buckets.len();
+        let mut next = (hole + 1) % cap;
+        loop {
+            let Some((k, _)) = &self.buckets[next] else {
+                break;
+            };
+            // `next` may only move into `hole` if its ideal bucket is not
+            // cyclically in `(hole, next]`; otherwise a forward probe from
+            // that bucket would no longer pass through `hole`.
+            let ideal = self.hash(k);
+            if (next + cap - ideal) % cap >= (next + cap - hole) % cap {
+                self.buckets.swap(hole, next);
+                hole = next;
+            }
+            next = (next + 1) % cap;
+        }
+    }
+
+    /// Returns an [`Entry`] for in-place update: look up `key` once and
+    /// either mutate the existing value or insert a new one, instead of a
+    /// separate `get`/`insert` pair that would hash and probe twice.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        if self.size * 10 >= self.buckets.len() * 7 {
+            self.resize();
+        }
+
+        let mut index = self.hash(&key);
+        for _ in 0..self.buckets.len() {
+            match &self.buckets[index] {
+                Some((k, _)) if *k == key => {
+                    return Entry::Occupied(OccupiedEntry { map: self, index });
+                }
+                None => {
+                    return Entry::Vacant(VacantEntry {
+                        map: self,
+                        key,
+                        index,
+                    });
+                }
+                _ => {
+                    index = (index + 1) % self.buckets.len();
+                }
+            }
+        }
+        panic!("HashMap full!");
+    }
+
     pub fn iter(&self) -> Iter<'_, K, V> {
         Iter {
             inner: self.buckets.iter(),
@@ -100,6 +187,82 @@ impl<K: Eq + Hash, V> HashMap<K, V> {
     }
 }
 
+/// A view into a single entry, obtained from [`HashMap::entry`].
+pub enum Entry<'a, K, V> {
+    Occupied(OccupiedEntry<'a, K, V>),
+    Vacant(VacantEntry<'a, K, V>),
+}
+
+impl<'a, K, V> Entry<'a, K, V> {
+    /// Inserts `default` if vacant, then returns a mutable reference to the
+    /// value either way.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    /// Like [`Self::or_insert`], but only computes the default on a vacant
+    /// entry.
+    pub fn or_insert_with(self, default: impl FnOnce() -> V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Runs `f` against the value if the entry is occupied, leaving it
+    /// untouched otherwise.
+    pub fn and_modify(mut self, f: impl FnOnce(&mut V)) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+/// An existing `(key, value)` pair found by [`HashMap::entry`].
+pub struct OccupiedEntry<'a, K, V> {
+    map: &'a mut HashMap<K, V>,
+    index: usize,
+}
+
+impl<'a, K, V> OccupiedEntry<'a, K, V> {
+    pub fn get(&self) -> &V {
+        self.map.buckets[self.index].as_ref().map(|(_, v)| v).unwrap()
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        self.map.buckets[self.index].as_mut().map(|(_, v)| v).unwrap()
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        self.map.buckets[self.index].as_mut().map(|(_, v)| v).unwrap()
+    }
+
+    /// Replaces the value, returning the one it displaced.
+    pub fn insert(&mut self, value: V) -> V {
+        let (_, slot) = self.map.buckets[self.index].as_mut().unwrap();
+        core::mem::replace(slot, value)
+    }
+}
+
+/// A vacant slot found by [`HashMap::entry`], ready to be filled.
+pub struct VacantEntry<'a, K, V> {
+    map: &'a mut HashMap<K, V>,
+    key: K,
+    index: usize,
+}
+
+impl<'a, K, V> VacantEntry<'a, K, V> {
+    pub fn insert(self, value: V) -> &'a mut V {
+        self.map.buckets[self.index] = Some((self.key, value));
+        self.map.size += 1;
+        self.map.buckets[self.index].as_mut().map(|(_, v)| v).unwrap()
+    }
+}
+
 pub struct Iter<'a, K, V> {
     inner: core::slice::Iter<'a, Option<(K, V)>>,
 }